@@ -16,9 +16,14 @@
 //! limitations under the License.
 
 #![no_std]
+#![feature(asm)]
 #![feature(panic_info_message)]
 use core::panic::PanicInfo;
 
+mod port;
+pub mod serial;
+pub mod vga;
+
 /// Replaces the panic handler from the standard library which is not available
 /// when using `#![no_std]` in a binary.
 ///
@@ -21,8 +21,10 @@
 #![no_main]
 #![feature(asm)]
 
+use core::fmt::{self, Write};
 use core::panic::PanicInfo;
-use myros::{vga, print, println};
+use myros::{vga, serial, print, println};
+use myros::vga::{Color, Colors, Console};
 
 #[no_mangle]
 extern "C" fn main() {
@@ -49,13 +51,230 @@ extern "C" {
 /// Replaces the panic handler from the standard library which is not available
 /// when using `#![no_std]` in a binary.
 ///
+/// Clears the screen to a fixed white-on-blue scheme and displays the panic location, message,
+/// and a general-purpose register dump, word-wrapped and centered. Uses [`vga::recover_console`]
+/// rather than [`vga::console`] so a readable fatal screen can still be shown even if the panic
+/// happened while the normal console's lock was held.
+///
 /// Does not return.
 #[panic_handler]
 pub fn panic(info: &PanicInfo) -> ! {
-    print!("kernel {}", info);
+    // captured before the rest of this handler runs; the panic machinery and this handler's
+    // prologue have already run, so the values are those live at this call
+    let registers = Registers::capture();
+
+    let mut text_buf = [0u8; 768];
+    let mut text = FixedWriter::new(&mut text_buf);
+    match info.location() {
+        Some(location) => {
+            let _ = write!(text, "panic at {}:{}:{}", location.file(), location.line(),
+                location.column());
+        },
+        None => {
+            let _ = write!(text, "panic");
+        },
+    }
+    if let Some(message) = info.message() {
+        let _ = write!(text, " - {}", message);
+    }
+    let _ = write!(text, "\n\n{}", registers);
+
+    // write to the serial port first so the panic message survives even if the display is
+    // wedged, matching the "serial first" convention used by vga::_print
+    if serial::mirror_enabled() {
+        let _ = writeln!(serial::com1(), "{}", text.as_str());
+    }
+
+    let mut lines: [&str; Console::HEIGHT] = [""; Console::HEIGHT];
+    let n_lines = word_wrap(text.as_str(), Console::WIDTH, &mut lines);
+
+    let console = vga::recover_console();
+    console.fill(Colors::new_from(Color::White, Color::Blue));
+
+    for _ in 0..(Console::HEIGHT.saturating_sub(n_lines)) / 2 {
+        console.write_centered("");
+    }
+    for line in &lines[..n_lines] {
+        console.write_centered(line);
+    }
+
     halt();
 }
 
+/// A snapshot of the x86_64 general-purpose registers, taken by [`Registers::capture`].
+#[derive(Debug, Clone, Copy)]
+struct Registers {
+    rax: u64,
+    rbx: u64,
+    rcx: u64,
+    rdx: u64,
+    rsi: u64,
+    rdi: u64,
+    rbp: u64,
+    rsp: u64,
+    r8: u64,
+    r9: u64,
+    r10: u64,
+    r11: u64,
+    r12: u64,
+    r13: u64,
+    r14: u64,
+    r15: u64,
+}
+
+impl Registers {
+    /// Captures the general-purpose register state as seen inside this function. Called as the
+    /// very first statement of the panic handler, before the rest of the handler runs; the panic
+    /// machinery and the handler's prologue have run already, and each `asm!` below may find a
+    /// register already holding an earlier one's output, so the values are not those live when
+    /// the panic was triggered. `#[inline(never)]` keeps this call
+    /// from being merged into the caller, where the optimizer would be free to reorder it past
+    /// code that uses these registers for its own purposes.
+    #[inline(never)]
+    fn capture() -> Registers {
+        let rax: u64;
+        let rbx: u64;
+        let rcx: u64;
+        let rdx: u64;
+        let rsi: u64;
+        let rdi: u64;
+        let rbp: u64;
+        let rsp: u64;
+        let r8: u64;
+        let r9: u64;
+        let r10: u64;
+        let r11: u64;
+        let r12: u64;
+        let r13: u64;
+        let r14: u64;
+        let r15: u64;
+
+        unsafe {
+            // SAFETY: sound because each of these just copies a named register's current value
+            // into a freshly allocated local, with no other side effects.
+            asm!("mov {}, rax", out(reg) rax, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, rbx", out(reg) rbx, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, rcx", out(reg) rcx, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, rdx", out(reg) rdx, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, rsi", out(reg) rsi, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, rdi", out(reg) rdi, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, rbp", out(reg) rbp, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, rsp", out(reg) rsp, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, r8",  out(reg) r8,  options(nomem, nostack, preserves_flags));
+            asm!("mov {}, r9",  out(reg) r9,  options(nomem, nostack, preserves_flags));
+            asm!("mov {}, r10", out(reg) r10, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, r11", out(reg) r11, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, r12", out(reg) r12, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, r13", out(reg) r13, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, r14", out(reg) r14, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, r15", out(reg) r15, options(nomem, nostack, preserves_flags));
+        }
+
+        Registers { rax, rbx, rcx, rdx, rsi, rdi, rbp, rsp, r8, r9, r10, r11, r12, r13, r14, r15 }
+    }
+}
+
+impl fmt::Display for Registers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "rax={:016x} rbx={:016x} rcx={:016x} rdx={:016x}",
+            self.rax, self.rbx, self.rcx, self.rdx)?;
+        writeln!(f, "rsi={:016x} rdi={:016x} rbp={:016x} rsp={:016x}",
+            self.rsi, self.rdi, self.rbp, self.rsp)?;
+        writeln!(f, "r8 ={:016x} r9 ={:016x} r10={:016x} r11={:016x}",
+            self.r8, self.r9, self.r10, self.r11)?;
+        write!(f, "r12={:016x} r13={:016x} r14={:016x} r15={:016x}",
+            self.r12, self.r13, self.r14, self.r15)
+    }
+}
+
+/// A `fmt::Write` sink backed by a fixed-size buffer, for formatting text in the panic handler
+/// where no heap allocator is available. Writes that would overflow the buffer are dropped
+/// (rather than truncated mid-character) so the buffer always holds valid UTF-8.
+struct FixedWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> FixedWriter<'a> {
+    fn new(buf: &'a mut [u8]) -> FixedWriter<'a> {
+        FixedWriter { buf, len: 0 }
+    }
+
+    fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.buf[..self.len]).unwrap_or("")
+    }
+}
+
+impl<'a> fmt::Write for FixedWriter<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let remaining = self.buf.len() - self.len;
+        if s.len() <= remaining {
+            self.buf[self.len..self.len + s.len()].copy_from_slice(s.as_bytes());
+            self.len += s.len();
+        }
+
+        Ok(())
+    }
+}
+
+/// Word-wraps `text` to `width` columns, writing each output line into `lines` and returning how
+/// many were written. Explicit `'\n'`s in `text` force a line break. Stops once `lines` is full.
+fn word_wrap<'a>(text: &'a str, width: usize, lines: &mut [&'a str]) -> usize {
+    let mut n = 0;
+
+    for paragraph in text.split('\n') {
+        if n >= lines.len() {
+            break;
+        }
+
+        if paragraph.is_empty() {
+            lines[n] = "";
+            n += 1;
+            continue;
+        }
+
+        n += word_wrap_paragraph(paragraph, width, &mut lines[n..]);
+    }
+
+    n
+}
+
+/// Greedily word-wraps a single, newline-free `paragraph` to `width` columns. A word longer than
+/// `width` is hard-broken since there's nowhere else to split it.
+fn word_wrap_paragraph<'a>(paragraph: &'a str, width: usize, lines: &mut [&'a str]) -> usize {
+    let mut n = 0;
+    let mut remaining = paragraph;
+
+    while n < lines.len() && !remaining.is_empty() {
+        remaining = remaining.trim_start_matches(' ');
+        if remaining.is_empty() {
+            break;
+        }
+
+        if remaining.chars().count() <= width {
+            lines[n] = remaining;
+            n += 1;
+            break;
+        }
+
+        let mut byte_end = 0;
+        let mut last_space = None;
+        for (i, c) in remaining.char_indices().take(width) {
+            if c == ' ' {
+                last_space = Some(i);
+            }
+            byte_end = i + c.len_utf8();
+        }
+
+        let break_at = last_space.unwrap_or(byte_end);
+        lines[n] = &remaining[..break_at];
+        n += 1;
+        remaining = &remaining[break_at..];
+    }
+
+    n
+}
+
 /// Halt execution. If halting due to a failure, use `panic` instead.
 ///
 /// Does not return.
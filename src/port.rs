@@ -0,0 +1,42 @@
+//! Low-level x86 I/O port access, used internally by device drivers.
+//
+//  Copyright 2020 Mike Leany
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//      <http://www.apache.org/licenses/LICENSE-2.0>
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Reads a byte from I/O port `port`. The caller must ensure that reading a byte from `port` has
+/// no unsafe side effects.
+pub(crate) unsafe fn inb(port: u16) -> u8 {
+    let value: u8;
+
+    asm!(
+        "in al, dx",
+        in("dx") port,
+        out("al") value,
+        options(nomem, nostack, preserves_flags),
+    );
+
+    value
+}
+
+/// Writes `value` to I/O port `port`. The caller must ensure that writing `value` to `port` has
+/// no unsafe side effects.
+pub(crate) unsafe fn outb(port: u16, value: u8) {
+    asm!(
+        "out dx, al",
+        in("dx") port,
+        in("al") value,
+        options(nomem, nostack, preserves_flags),
+    );
+}
@@ -0,0 +1,113 @@
+//! Serial (16550 UART) driver
+//
+//  Copyright 2020 Mike Leany
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//      <http://www.apache.org/licenses/LICENSE-2.0>
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+///////////////////////////////////////////////////////////////////////////////////////////////////
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+use crate::port::{inb, outb};
+
+/// A driver for a 16550-compatible UART exposed as a serial port.
+#[derive(Debug, Clone)]
+pub struct SerialPort(&'static Mutex<SerialPortData>);
+
+impl Write for SerialPort {
+    /// Writes a string to the `SerialPort`, byte by byte in its UTF-8 encoding.
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let data = self.0.lock();
+
+        for byte in s.bytes() {
+            data.write_byte(byte);
+        }
+
+        Ok(())
+    }
+}
+
+/// The data for a `SerialPort`.
+#[derive(Debug)]
+struct SerialPortData {
+    base: u16,
+}
+
+impl SerialPortData {
+    fn init(&self) {
+        unsafe {
+            // SAFETY: sound because `self.base` is the base port of a 16550 UART, and the
+            // sequence below is the standard initialization sequence for such a UART: disable
+            // interrupts, set the baud-rate divisor (38400 baud) via DLAB, switch to 8N1 and
+            // clear DLAB, then enable and clear the FIFOs.
+            outb(self.base + 1, 0x00);
+            outb(self.base + 3, 0x80);
+            outb(self.base, 0x03);
+            outb(self.base + 1, 0x00);
+            outb(self.base + 3, 0x03);
+            outb(self.base + 2, 0xc7);
+        }
+    }
+
+    fn write_byte(&self, byte: u8) {
+        while !self.transmit_ready() { }
+
+        unsafe {
+            // SAFETY: sound because `self.base` is the base port of a 16550 UART, and
+            // `transmit_ready` just confirmed that its transmitter holding register is empty.
+            outb(self.base, byte);
+        }
+    }
+
+    fn transmit_ready(&self) -> bool {
+        const LINE_STATUS_OFFSET: u16 = 5;
+        const THR_EMPTY: u8 = 0x20;
+
+        unsafe {
+            // SAFETY: sound because `self.base` is the base port of a 16550 UART, and reading
+            // its line-status register has no unsafe side effects.
+            inb(self.base + LINE_STATUS_OFFSET) & THR_EMPTY != 0
+        }
+    }
+}
+
+// SAFETY: sound because only one instance of `SerialPortData` is ever created per base port, and
+// access to it is synchronized using a Mutex.
+unsafe impl Send for SerialPortData { }
+
+/// Returns a handle to the COM1 `SerialPort`. The underlying UART is initialized on first call.
+/// Writes to the `SerialPort` are synchronized and are thus thread safe.
+pub fn com1() -> SerialPort {
+    const COM1_BASE: u16 = 0x3f8;
+    static SERIAL: Mutex<SerialPortData> = Mutex::new(SerialPortData { base: COM1_BASE });
+    static INIT: spin::Once<()> = spin::Once::new();
+
+    INIT.call_once(|| {
+        SERIAL.lock().init();
+    });
+
+    SerialPort(&SERIAL)
+}
+
+static MIRROR_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Enables or disables mirroring of everything printed with `print!`/`println!` to COM1. This
+/// gives headless logging for CI/QEMU runs where the VGA buffer isn't scraped, and makes panic
+/// messages survive a wedged display. It is disabled by default.
+pub fn set_serial_mirror(enabled: bool) {
+    MIRROR_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns whether mirroring to COM1, as set by [`set_serial_mirror`], is currently enabled.
+pub fn mirror_enabled() -> bool {
+    MIRROR_ENABLED.load(Ordering::Relaxed)
+}
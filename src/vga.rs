@@ -19,6 +19,7 @@ use core::convert::TryFrom;
 use core::ops;
 use lazy_static::lazy_static;
 use spin::Mutex;
+use crate::port::outb;
 
 /// A struct to represent the VGA console.
 #[derive(Debug, Clone)]
@@ -29,10 +30,84 @@ impl Console {
     pub const WIDTH: usize = 80;
     /// The number of lines on the screen.
     pub const HEIGHT: usize = 25;
-    /// The number of lines in the buffer.
-    pub const BUFFER_LINES: usize = Self::HEIGHT + 1;
+    /// The number of lines retained in the scrollback ring buffer.
+    pub const SCROLLBACK_LINES: usize = 256;
     /// The number of `Glyphs` that will fit between tab stops.
     pub const TAB_WIDTH: usize = 8;
+    /// A full-height cursor, covering every scanline of the character cell. Pass to
+    /// [`set_cursor_shape`](#method.set_cursor_shape).
+    pub const CURSOR_BLOCK: (u8, u8) = (0, 15);
+    /// An underline cursor, covering only the bottom two scanlines of the character cell. Pass to
+    /// [`set_cursor_shape`](#method.set_cursor_shape).
+    pub const CURSOR_UNDERLINE: (u8, u8) = (14, 15);
+
+    /// Enables or disables the `$NAME$` inline color-markup dialect (e.g. `$RED$`, `$BG_BLUE$`,
+    /// `$RESET$`) recognized by [`write_str`](#method.write_str). It is disabled by default, so
+    /// raw text containing a literal `$` is unaffected unless this is called. Returns the
+    /// previous setting.
+    pub fn set_markup_enabled(&self, enabled: bool) -> bool {
+        core::mem::replace(&mut self.0.lock().markup_enabled, enabled)
+    }
+
+    /// Scrolls the visible window up (toward older output) by `n` lines. Lines older than the
+    /// retained scrollback are displayed blank.
+    pub fn scroll_up(&self, n: usize) {
+        self.0.lock().scroll_up(n);
+    }
+
+    /// Scrolls the visible window down (toward the most recent output) by `n` lines. Scrolling
+    /// down past the bottom has no further effect; use [`scroll_to_bottom`](#method.scroll_to_bottom)
+    /// to jump straight there.
+    pub fn scroll_down(&self, n: usize) {
+        self.0.lock().scroll_down(n);
+    }
+
+    /// Scrolls the visible window all the way down to the most recently written line.
+    pub fn scroll_to_bottom(&self) {
+        self.0.lock().scroll_to_bottom();
+    }
+
+    /// Shows or hides the blinking hardware cursor. It tracks the location of the next `Glyph`
+    /// that will be written.
+    pub fn set_cursor_visible(&self, visible: bool) {
+        let mut data = self.0.lock();
+        data.cursor_visible = visible;
+        data.update_cursor_shape();
+    }
+
+    /// Sets the scanline range (`0`-`15`, top to bottom) of the blinking hardware cursor, e.g.
+    /// [`CURSOR_BLOCK`](#associatedconstant.CURSOR_BLOCK) or
+    /// [`CURSOR_UNDERLINE`](#associatedconstant.CURSOR_UNDERLINE) for a block or underline
+    /// cursor, or any other range for a custom shape.
+    pub fn set_cursor_shape(&self, start_scanline: u8, end_scanline: u8) {
+        let mut data = self.0.lock();
+        data.cursor_start_scanline = start_scanline & 0x1f;
+        data.cursor_end_scanline = end_scanline & 0x1f;
+        data.update_cursor_shape();
+    }
+
+    /// Fills the entire visible screen with blank glyphs using `colors`, and makes `colors` the
+    /// `Colors` used for subsequently written `Glyph`s. The scrollback lines in the bottom window
+    /// are blanked with `colors` as well, so later writes keep the filled background; `loc` is
+    /// left unchanged. Useful for full-screen redraws such as a panic screen.
+    pub fn fill(&self, colors: Colors) {
+        self.0.lock().fill(colors);
+    }
+
+    /// Writes `s` on the current line, horizontally centered, then advances to the next line.
+    /// Long lines which don't fit aren't word-wrapped, they just continue on the next line;
+    /// callers that need to center a block of text should wrap it themselves and call this once
+    /// per line.
+    pub fn write_centered(&self, s: &str) {
+        let padding = Console::WIDTH.saturating_sub(s.chars().count()) / 2;
+        let mut console = self.clone();
+
+        for _ in 0..padding {
+            let _ = console.write_str(" ");
+        }
+        let _ = console.write_str(s);
+        let _ = console.write_str("\n");
+    }
 }
 
 impl Write for Console {
@@ -44,6 +119,10 @@ impl Write for Console {
     /// - The carriage return (`'\r'`) is ignored.
     /// - The tab (`'\t'`) advances to the next tab stop.
     ///
+    /// ANSI/SGR color escape sequences (`ESC [ <params> m`) are also recognized and change the
+    /// `Colors` used for subsequently written `Glyph`s rather than being displayed. A sequence
+    /// split across two calls to `write_str` is still recognized correctly.
+    ///
     /// All other characters in the string are first converted to `Glyph`s, then written to the
     /// screen. Any characters that cannot be converted are replaced with `Glyph::REPLACEMENT`.
     fn write_str(&mut self, s: &str) -> fmt::Result {
@@ -56,107 +135,848 @@ impl Write for Console {
 /// The data for a `Console`.
 #[derive(Debug)]
 struct ConsoleData {
-    video_mem: *mut [ColoredGlyph; Console::WIDTH],
-    buffer: [[ColoredGlyph; Console::WIDTH]; Console::BUFFER_LINES],
+    backend: Backend,
+    buffer: [[ColoredGlyph; Console::WIDTH]; Console::SCROLLBACK_LINES],
     colors: Colors,
     loc: Location,
+    /// The number of lines the visible window is scrolled up from the bottom (most recent
+    /// output). `0` means the bottom of the scrollback is shown.
+    view_offset: usize,
+    escape: EscapeState,
+    markup_enabled: bool,
+    markup: MarkupState,
+    cursor_visible: bool,
+    cursor_start_scanline: u8,
+    cursor_end_scanline: u8,
 }
 
 impl ConsoleData {
+    /// Builds a fresh `ConsoleData` backed by the VGA text-mode `Backend`, in its default state.
+    const fn new() -> ConsoleData {
+        Self::new_with_backend(Backend::Text(TextBackend::new()))
+    }
+
+    /// Builds a fresh `ConsoleData` using the given hardware `backend`, otherwise in its default
+    /// state.
+    const fn new_with_backend(backend: Backend) -> ConsoleData {
+        ConsoleData {
+            backend,
+            buffer: [[ColoredGlyph::null(Colors::new()); Console::WIDTH]; Console::SCROLLBACK_LINES],
+            colors: Colors::new(),
+            loc: Location::new(),
+            view_offset: 0,
+            escape: EscapeState::Ground,
+            markup_enabled: false,
+            markup: MarkupState::Ground,
+            cursor_visible: false,
+            cursor_start_scanline: Console::CURSOR_UNDERLINE.0,
+            cursor_end_scanline: Console::CURSOR_UNDERLINE.1,
+        }
+    }
+
     fn write_str(&mut self, s: &str) {
         let mut new_loc = self.loc;
 
         for c in s.chars() {
-            new_loc = match c {
-                '\n' => { new_loc.next_line() },
-                '\t' => { new_loc.next_tab() },
-                '\r' => { new_loc },
-                _ => {
-                    // write the glyph
-                    let buf_line = new_loc.line() % Console::BUFFER_LINES;
-                    self.buffer[buf_line][new_loc.col()] = ColoredGlyph {
-                        glyph: Glyph::try_from(c).unwrap_or(Glyph::REPLACEMENT),
-                        colors: self.colors,
-                    };
-
-                    new_loc + 1
-                },
-            };
+            if self.handle_escape(c) {
+                continue;
+            }
 
-            if new_loc.line() > self.loc.line() {
-                self.buffer[new_loc.line() % Console::BUFFER_LINES]
-                    = [ColoredGlyph::null(self.colors); Console::WIDTH];
-                self.scroll_and_flush(new_loc);
+            if self.handle_markup(c, &mut new_loc) {
+                continue;
             }
+
+            new_loc = self.write_char(new_loc, c);
         }
         self.flush(new_loc);
     }
 
-    fn scroll_and_flush(&mut self, new_loc: Location) {
-        let top_line = (new_loc.line() + 1).saturating_sub(Console::HEIGHT);
+    /// Writes a single `char` at `loc`, handling the newline, tab and carriage-return special
+    /// cases and scrolling the buffer if the new location has advanced to the next line. Returns
+    /// the `Location` following the written `char`.
+    fn write_char(&mut self, loc: Location, c: char) -> Location {
+        let new_loc = match c {
+            '\n' => { loc.next_line() },
+            '\t' => { loc.next_tab() },
+            '\r' => { loc },
+            _ => {
+                // write the glyph
+                let buf_line = loc.line() % Console::SCROLLBACK_LINES;
+                self.buffer[buf_line][loc.col()] = ColoredGlyph {
+                    glyph: Glyph::try_from(c).unwrap_or(Glyph::REPLACEMENT),
+                    colors: self.colors,
+                };
+
+                loc + 1
+            },
+        };
+
+        if new_loc.line() > loc.line() {
+            self.buffer[new_loc.line() % Console::SCROLLBACK_LINES]
+                = [ColoredGlyph::null(self.colors); Console::WIDTH];
+            self.scroll_and_flush(new_loc);
+        }
+
+        new_loc
+    }
+
+    /// Feeds a single `char` through the `$NAME$` color-markup state machine, updating
+    /// `self.colors` as tokens complete. Returns `true` if the `char` was consumed by the state
+    /// machine and should not be written to the screen as a glyph by the caller. Unterminated or
+    /// unknown tokens are written to the screen verbatim, including their `$` delimiters.
+    fn handle_markup(&mut self, c: char, loc: &mut Location) -> bool {
+        if !self.markup_enabled {
+            return false;
+        }
+
+        match self.markup {
+            MarkupState::Ground => {
+                if c == '$' {
+                    self.markup = MarkupState::Token { buf: [0; MarkupState::MAX_TOKEN_LEN], len: 0 };
+                    true
+                } else {
+                    false
+                }
+            },
+            MarkupState::Token { mut buf, mut len } => {
+                if c == '$' {
+                    self.markup = MarkupState::Ground;
+
+                    // `buf` is only ever filled from `char::is_ascii_alphanumeric` or `_`, so
+                    // `buf[..len]` is always valid UTF-8.
+                    let token = core::str::from_utf8(&buf[..len]).unwrap_or("");
+                    if !self.apply_markup_token(token) {
+                        *loc = self.write_char(*loc, '$');
+                        for &byte in &buf[..len] {
+                            *loc = self.write_char(*loc, byte as char);
+                        }
+                        *loc = self.write_char(*loc, '$');
+                    }
+                } else if len < MarkupState::MAX_TOKEN_LEN && (c.is_ascii_alphanumeric() || c == '_') {
+                    buf[len] = c as u8;
+                    len += 1;
+                    self.markup = MarkupState::Token { buf, len };
+                } else {
+                    // a character that can't appear in a token ends it unterminated; flush what
+                    // was buffered verbatim, then let the caller process `c` normally
+                    self.markup = MarkupState::Ground;
+                    *loc = self.write_char(*loc, '$');
+                    for &byte in &buf[..len] {
+                        *loc = self.write_char(*loc, byte as char);
+                    }
+                    return false;
+                }
+                true
+            },
+        }
+    }
+
+    /// Applies a completed `$NAME$` markup token to `self.colors`. Returns `false` if `token`
+    /// isn't a recognized token name.
+    fn apply_markup_token(&mut self, token: &str) -> bool {
+        if token == "RESET" {
+            self.colors = Colors::new();
+            true
+        } else if let Some(name) = token.strip_prefix("BG_") {
+            match color_from_markup_name(name) {
+                Some(color) => { self.colors.set_background_color(color); true },
+                None => false,
+            }
+        } else {
+            match color_from_markup_name(token) {
+                Some(color) => { self.colors.set_text_color(color); true },
+                None => false,
+            }
+        }
+    }
+
+    /// Feeds a single `char` through the CSI SGR escape-sequence state machine, updating
+    /// `self.colors` as sequences complete. Returns `true` if the `char` was consumed by the
+    /// state machine and should not be written to the screen as a glyph.
+    fn handle_escape(&mut self, c: char) -> bool {
+        match self.escape {
+            EscapeState::Ground => {
+                if c == '\u{1b}' {
+                    self.escape = EscapeState::Escape;
+                    true
+                } else {
+                    false
+                }
+            },
+            EscapeState::Escape => {
+                self.escape = if c == '[' {
+                    EscapeState::Csi { params: [0; EscapeState::MAX_PARAMS], count: 0, has_digit: false }
+                } else {
+                    // not a CSI sequence; silently drop the escape
+                    EscapeState::Ground
+                };
+                true
+            },
+            EscapeState::Csi { mut params, mut count, mut has_digit } => {
+                match c {
+                    '0'..='9' => {
+                        if count < EscapeState::MAX_PARAMS {
+                            let digit = c as u16 - '0' as u16;
+                            params[count] = params[count].saturating_mul(10).saturating_add(digit);
+                            has_digit = true;
+                        }
+                        self.escape = EscapeState::Csi { params, count, has_digit };
+                    },
+                    ';' => {
+                        // once `count` reaches `MAX_PARAMS`, further params' digits are dropped
+                        if count < EscapeState::MAX_PARAMS {
+                            count += 1;
+                        }
+                        has_digit = false;
+                        self.escape = EscapeState::Csi { params, count, has_digit };
+                    },
+                    'm' => {
+                        if (has_digit || count > 0) && count < EscapeState::MAX_PARAMS {
+                            count += 1;
+                        }
+                        self.apply_sgr(&params[..count]);
+                        self.escape = EscapeState::Ground;
+                    },
+                    ' '..='/' | ':'..='?' => {
+                        // other parameter and intermediate bytes; stay in the sequence until its
+                        // final byte
+                        self.escape = EscapeState::Csi { params, count, has_digit };
+                    },
+                    _ => {
+                        // unrecognized final byte; consume the whole sequence silently
+                        self.escape = EscapeState::Ground;
+                    },
+                }
+                true
+            },
+        }
+    }
 
-        for (scr_line, line) in (top_line..).take(Console::HEIGHT).enumerate() {
-            let buf_line = line % Console::BUFFER_LINES;
+    /// Applies a completed `ESC [ params m` SGR sequence to `self.colors`.
+    fn apply_sgr(&mut self, params: &[u16]) {
+        if params.is_empty() {
+            self.colors = Colors::new();
+            return;
+        }
 
-            unsafe {
-                // SAFETY: sound because `self.video_mem` should always point to a location of
-                // Console::HEIGHT lines that we have access to, and `scr_line` is always less than
-                // `Console::HEIGHT`. Also, access to `ConsoleData`, which is private, is synchronized
-                // using a Mutex, which prevents data races.
-                self.video_mem.add(scr_line).write_volatile(self.buffer[buf_line]);
+        // bold (1) is applied after the rest of the params regardless of where it appears in the
+        // sequence, so `ESC[1;31m` (bold red) and `ESC[31;1m` both promote the text color to
+        // bright, rather than only the latter.
+        let mut bold = false;
+        for &param in params {
+            match param {
+                0 => { self.colors = Colors::new(); bold = false; },
+                1 => bold = true,
+                30..=37 => self.colors.set_text_color(ansi_color((param - 30) as u8)),
+                40..=47 => self.colors.set_background_color(ansi_color((param - 40) as u8)),
+                90..=97 => self.colors.set_text_color(ansi_color((param - 90) as u8).brighten()),
+                100..=107 => self.colors.set_background_color(ansi_color((param - 100) as u8).brighten()),
+                _ => { },
             }
         }
 
+        if bold {
+            self.colors.set_text_color(self.colors.text().brighten());
+        }
+    }
+
+    fn scroll_and_flush(&mut self, new_loc: Location) {
+        // a new line was just written, so any scrolled-up view snaps back to the bottom
+        self.view_offset = 0;
+        self.render_window(new_loc.line());
+
         self.loc = new_loc;
+        self.update_cursor_location();
     }
 
     fn flush(&mut self, new_loc: Location) {
-        let scr_line = core::cmp::min(new_loc.line(), Console::HEIGHT - 1);
-        let buf_line = new_loc.line() % Console::BUFFER_LINES;
+        if self.view_offset != 0 {
+            // new writes snap the view back to the bottom before flushing
+            self.view_offset = 0;
+            self.render_window(new_loc.line());
+        } else {
+            let top = Self::top_line(new_loc.line());
+            self.render_row(new_loc.line() - top, new_loc.line());
+        }
+
+        self.loc = new_loc;
+        self.update_cursor_location();
+    }
+
+    /// Returns the line shown in the top screen row of the window, given that `bottom_line` is the
+    /// bottommost line of the window. Lines fill the screen top-down until there are enough of
+    /// them to fill the window, after which the window scrolls with `bottom_line`.
+    fn top_line(bottom_line: usize) -> usize {
+        bottom_line.saturating_sub(Console::HEIGHT - 1)
+    }
+
+    /// Returns the scrollback line that should be displayed in screen row `scr_line`, given that
+    /// `bottom_line` is the most recently written line and the view is scrolled up by
+    /// `self.view_offset` lines. Returns `None` if that row falls before the first line ever
+    /// written, or before the oldest line still retained in the ring buffer, in which case the
+    /// row should be rendered blank.
+    fn line_for_screen_row(&self, bottom_line: usize, scr_line: usize) -> Option<usize> {
+        let effective_bottom = bottom_line.saturating_sub(self.view_offset);
+        let line = Self::top_line(effective_bottom) + scr_line;
+
+        if line > effective_bottom || bottom_line - line >= Console::SCROLLBACK_LINES {
+            None
+        } else {
+            Some(line)
+        }
+    }
+
+    /// Writes screen row `scr_line` from the scrollback, given that `bottom_line` is the most
+    /// recently written line.
+    fn render_row(&mut self, scr_line: usize, bottom_line: usize) {
+        let row = match self.line_for_screen_row(bottom_line, scr_line) {
+            Some(line) => self.buffer[line % Console::SCROLLBACK_LINES],
+            None => [ColoredGlyph::null(self.colors); Console::WIDTH],
+        };
+
+        self.backend.blit_line(scr_line, &row);
+    }
+
+    /// Re-renders every row of the visible window from the scrollback, given that `bottom_line`
+    /// is the most recently written line.
+    fn render_window(&mut self, bottom_line: usize) {
+        for scr_line in 0..Console::HEIGHT {
+            self.render_row(scr_line, bottom_line);
+        }
+    }
+
+    fn scroll_up(&mut self, n: usize) {
+        self.view_offset = self.view_offset.saturating_add(n);
+        self.render_window(self.loc.line());
+    }
+
+    fn scroll_down(&mut self, n: usize) {
+        self.view_offset = self.view_offset.saturating_sub(n);
+        self.render_window(self.loc.line());
+    }
+
+    fn scroll_to_bottom(&mut self) {
+        self.view_offset = 0;
+        self.render_window(self.loc.line());
+    }
+
+    /// Programs the CRTC cursor-location registers so the blinking hardware cursor tracks
+    /// `self.loc`. A no-op unless the active backend is VGA text mode, since the blinking
+    /// hardware cursor is a text-mode-only concept.
+    fn update_cursor_location(&self) {
+        if !matches!(self.backend, Backend::Text(_)) {
+            return;
+        }
+
+        let effective_bottom = self.loc.line().saturating_sub(self.view_offset);
+        let scr_line = effective_bottom - Self::top_line(effective_bottom);
+        let offset = (scr_line * Console::WIDTH + self.loc.col()) as u16;
 
         unsafe {
-            // SAFETY: sound because `self.video_mem` should always point to a location of
-            // Console::HEIGHT lines that we have access to, and `scr_line` is always less than
-            // `Console::HEIGHT`. Also, access to `ConsoleData`, which is private, is synchronized
-            // using a Mutex, which prevents data races.
-            self.video_mem.add(scr_line).write_volatile(self.buffer[buf_line]);
+            // SAFETY: sound because ports 0x3d4/0x3d5 are the standard CRTC index/data ports,
+            // and 0x0e/0x0f are the cursor-location-high/low registers, which have no unsafe side
+            // effects to write.
+            outb(CRTC_INDEX, CURSOR_LOCATION_HIGH);
+            outb(CRTC_DATA, (offset >> 8) as u8);
+            outb(CRTC_INDEX, CURSOR_LOCATION_LOW);
+            outb(CRTC_DATA, (offset & 0xff) as u8);
         }
+    }
 
-        self.loc = new_loc;
+    /// Programs the CRTC cursor-start/end registers from `self.cursor_visible`,
+    /// `self.cursor_start_scanline` and `self.cursor_end_scanline`. A no-op unless the active
+    /// backend is VGA text mode, since the blinking hardware cursor is a text-mode-only concept.
+    fn update_cursor_shape(&self) {
+        if !matches!(self.backend, Backend::Text(_)) {
+            return;
+        }
+
+        let disable_bit = if self.cursor_visible { 0x00 } else { 0x20 };
+
+        unsafe {
+            // SAFETY: sound because ports 0x3d4/0x3d5 are the standard CRTC index/data ports,
+            // and 0x0a/0x0b are the cursor-start/end registers, which have no unsafe side effects
+            // to write.
+            outb(CRTC_INDEX, CURSOR_START);
+            outb(CRTC_DATA, disable_bit | self.cursor_start_scanline);
+            outb(CRTC_INDEX, CURSOR_END);
+            outb(CRTC_DATA, self.cursor_end_scanline);
+        }
+    }
+
+    fn fill(&mut self, colors: Colors) {
+        let row = [ColoredGlyph::null(colors); Console::WIDTH];
+
+        // blank the bottom window of the scrollback and adopt `colors`, so that later writes and
+        // redraws from the scrollback keep the filled background
+        self.colors = colors;
+        let bottom_line = self.loc.line();
+        for line in Self::top_line(bottom_line)..=bottom_line {
+            self.buffer[line % Console::SCROLLBACK_LINES] = row;
+        }
+
+        for scr_line in 0..Console::HEIGHT {
+            self.backend.blit_line(scr_line, &row);
+        }
     }
 }
 
-// SAFETY: sound because only one instance of `ConsoleData` is ever created, and its pointer
-// `video_mem` is never accessed outside of `ConsoleData`. Also, access to the only instance of
-// `ConsoleData` is synchronized using a `Mutex`.
-unsafe impl Send for ConsoleData { }
+/// The CRTC index register port.
+const CRTC_INDEX: u16 = 0x3d4;
+/// The CRTC data register port.
+const CRTC_DATA: u16 = 0x3d5;
+/// The CRTC cursor-start register index. Bits 0-4 hold the cursor's starting scanline; bit 5
+/// disables (hides) the cursor.
+const CURSOR_START: u8 = 0x0a;
+/// The CRTC cursor-end register index. Bits 0-4 hold the cursor's ending scanline.
+const CURSOR_END: u8 = 0x0b;
+/// The CRTC cursor-location-high register index.
+const CURSOR_LOCATION_HIGH: u8 = 0x0e;
+/// The CRTC cursor-location-low register index.
+const CURSOR_LOCATION_LOW: u8 = 0x0f;
+
+/// A console hardware backend: something that can render `Glyph`s, in `Colors`, to a physical
+/// display. `ConsoleData` holds one behind the `Backend` enum and drives it, keeping the
+/// `print!`/`println!` line-wrapping, scrollback and markup/escape-sequence handling entirely
+/// backend-agnostic.
+trait ConsoleBackend {
+    /// Renders a single glyph at character cell (`row`, `col`).
+    fn putc(&mut self, row: usize, col: usize, glyph: Glyph, colors: Colors);
+
+    /// Renders an entire row of `Console::WIDTH` glyphs at once, e.g. when scrolling or
+    /// redrawing from the scrollback buffer. The default implementation calls `putc` once per
+    /// column; backends that can write a whole row more efficiently, such as `TextBackend`,
+    /// should override it.
+    fn blit_line(&mut self, row: usize, line: &[ColoredGlyph; Console::WIDTH]) {
+        for (col, cell) in line.iter().enumerate() {
+            self.putc(row, col, cell.glyph, cell.colors);
+        }
+    }
+}
+
+/// The active console hardware backend. A plain `enum` rather than a trait object, since this
+/// crate has no heap allocator to store one in.
+#[derive(Debug)]
+enum Backend {
+    /// Renders directly to the VGA text-mode video memory at `0xb8000`.
+    Text(TextBackend),
+    /// Renders glyphs as pixels into a linear RGB framebuffer.
+    Framebuffer(FramebufferConsole),
+}
+
+impl ConsoleBackend for Backend {
+    fn putc(&mut self, row: usize, col: usize, glyph: Glyph, colors: Colors) {
+        match self {
+            Backend::Text(backend) => backend.putc(row, col, glyph, colors),
+            Backend::Framebuffer(backend) => backend.putc(row, col, glyph, colors),
+        }
+    }
+
+    fn blit_line(&mut self, row: usize, line: &[ColoredGlyph; Console::WIDTH]) {
+        match self {
+            Backend::Text(backend) => backend.blit_line(row, line),
+            Backend::Framebuffer(backend) => backend.blit_line(row, line),
+        }
+    }
+}
+
+/// The VGA text-mode `ConsoleBackend`, writing directly to the `0xb8000` video memory.
+#[derive(Debug)]
+struct TextBackend {
+    video_mem: *mut [ColoredGlyph; Console::WIDTH],
+}
+
+impl TextBackend {
+    /// Builds a `TextBackend` pointing at the VGA text-mode video memory.
+    const fn new() -> TextBackend {
+        const VIDEO_MEM_ADDR: u64 = 0xb8000;
+
+        TextBackend {
+            video_mem: VIDEO_MEM_ADDR as *mut [ColoredGlyph; Console::WIDTH],
+        }
+    }
+}
+
+impl ConsoleBackend for TextBackend {
+    fn putc(&mut self, row: usize, col: usize, glyph: Glyph, colors: Colors) {
+        unsafe {
+            // SAFETY: sound because `self.video_mem` always points to a location of
+            // `Console::HEIGHT` lines of `Console::WIDTH` cells that we have access to, and
+            // `row`/`col` are always within those bounds. Also, access to `TextBackend`, which is
+            // private, is synchronized using a Mutex (via the `ConsoleData` that owns it).
+            (self.video_mem as *mut ColoredGlyph).add(row * Console::WIDTH + col)
+                .write_volatile(ColoredGlyph { glyph, colors });
+        }
+    }
+
+    fn blit_line(&mut self, row: usize, line: &[ColoredGlyph; Console::WIDTH]) {
+        unsafe {
+            // SAFETY: sound because `self.video_mem` always points to a location of
+            // `Console::HEIGHT` lines that we have access to, and `row` is always less than
+            // `Console::HEIGHT`. Also, access to `TextBackend`, which is private, is synchronized
+            // using a Mutex (via the `ConsoleData` that owns it).
+            self.video_mem.add(row).write_volatile(*line);
+        }
+    }
+}
+
+// SAFETY: sound because only one instance of `TextBackend` is ever created per video memory
+// address, and its pointer `video_mem` is never accessed outside of `TextBackend`. Also, access
+// to it is synchronized using a `Mutex` (via the `ConsoleData` that owns it).
+unsafe impl Send for TextBackend { }
+
+/// The pixel width and height of an embedded `FONT` glyph cell used by `FramebufferConsole`.
+const FONT_WIDTH: usize = 8;
+const FONT_HEIGHT: usize = 16;
+
+/// A `ConsoleBackend` that renders glyphs as pixels into a linear RGB framebuffer (e.g. as handed
+/// off by a VBE/GOP boot mode), using the embedded `FONT` bitmap font, rather than the VGA
+/// text-mode video memory used by `TextBackend`. Supports both 24- and 32-bit-per-pixel
+/// framebuffers.
+#[derive(Debug)]
+pub struct FramebufferConsole {
+    base: *mut u8,
+    pitch: usize,
+    bpp: u8,
+}
+
+impl FramebufferConsole {
+    /// Builds a `FramebufferConsole` rendering into the linear framebuffer at `base`, with
+    /// `pitch` bytes per row and `bpp` bytes per pixel (`3` or `4`).
+    ///
+    /// # Safety
+    ///
+    /// `bpp` must be `3` or `4`, and `base` must point to a writable framebuffer of at least
+    /// `Console::HEIGHT * FONT_HEIGHT` rows spaced `pitch` bytes apart, each with room for
+    /// `Console::WIDTH * FONT_WIDTH` pixels, that remains valid for as long as the returned
+    /// `FramebufferConsole` is used.
+    pub unsafe fn new(base: *mut u8, pitch: usize, bpp: u8) -> FramebufferConsole {
+        FramebufferConsole { base, pitch, bpp }
+    }
+
+    /// Writes a single pixel at (`x`, `y`), packed as `rgb` (`0x00RRGGBB`).
+    fn put_pixel(&mut self, x: usize, y: usize, rgb: u32) {
+        let bpp = self.bpp as usize;
+        let bytes = rgb.to_le_bytes();
+
+        unsafe {
+            // SAFETY: sound as long as the constructor's contract held, since `x` and `y` are
+            // always within `Console::WIDTH * FONT_WIDTH` and `Console::HEIGHT * FONT_HEIGHT`
+            // for values produced by `putc`.
+            let pixel = self.base.add(y * self.pitch + x * bpp);
+            core::ptr::copy_nonoverlapping(bytes.as_ptr(), pixel, bpp);
+        }
+    }
+}
+
+impl ConsoleBackend for FramebufferConsole {
+    fn putc(&mut self, row: usize, col: usize, glyph: Glyph, colors: Colors) {
+        let bitmap = &FONT[glyph.0 as usize];
+        let fg = PALETTE[colors.text() as usize];
+        let bg = PALETTE[colors.background() as usize];
+
+        for (font_row, &bits) in bitmap.iter().enumerate() {
+            let y = row * FONT_HEIGHT + font_row;
+            for bit in 0..FONT_WIDTH {
+                let pixel = if bits & (0x80 >> bit) != 0 { fg } else { bg };
+                self.put_pixel(col * FONT_WIDTH + bit, y, pixel);
+            }
+        }
+    }
+}
+
+// SAFETY: sound because only one instance of `FramebufferConsole` is ever created per
+// framebuffer, and its pointer `base` is never accessed outside of `FramebufferConsole`. Also,
+// access to it is synchronized using a `Mutex` (via the `ConsoleData` that owns it).
+unsafe impl Send for FramebufferConsole { }
+
+/// Maps each 4-bit VGA `Color` to a 24-bit `0x00RRGGBB` pixel value, using the standard VGA
+/// palette, for `FramebufferConsole` rendering.
+const PALETTE: [u32; 16] = [
+    0x000000, 0x0000aa, 0x00aa00, 0x00aaaa,
+    0xaa0000, 0xaa00aa, 0xaa5500, 0xaaaaaa,
+    0x555555, 0x5555ff, 0x55ff55, 0x55ffff,
+    0xff5555, 0xff55ff, 0xffff55, 0xffffff,
+];
+
+/// An embedded 8x16 bitmap font, indexed by `Glyph`, used by `FramebufferConsole`. Each glyph is
+/// 16 bytes, one per scanline, with bit 7 the left-most pixel. Only printable ASCII (`Glyph`
+/// values 0x20-0x7e) is populated; lowercase letters reuse their uppercase bitmap, and all other
+/// code points, including the rest of Code page 437, render as a blank cell.
+const FONT: [[u8; FONT_HEIGHT]; 256] = [
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x10,0x10,0x10,0x10,0x00,0x00,0x10,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x28,0x28,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x28,0x28,0x7c,0x28,0x7c,0x28,0x28,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x10,0x7c,0x50,0x50,0x7c,0x14,0x14,0x7c,0x10,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x44,0x08,0x08,0x10,0x20,0x20,0x44,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x30,0x48,0x48,0x30,0x54,0x48,0x34,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x10,0x10,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x04,0x08,0x30,0x40,0x20,0x18,0x04,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x40,0x20,0x18,0x04,0x08,0x30,0x40,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x44,0x28,0x28,0x7c,0x28,0x28,0x44,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x10,0x10,0x10,0x7c,0x10,0x10,0x10,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x10,0x20,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x7c,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x10,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x04,0x08,0x08,0x10,0x20,0x20,0x40,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x7c,0x4c,0x4c,0x54,0x64,0x64,0x7c,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x10,0x10,0x10,0x10,0x10,0x10,0x7c,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x7c,0x04,0x04,0x7c,0x40,0x40,0x7c,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x7c,0x04,0x04,0x7c,0x04,0x04,0x7c,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x44,0x44,0x44,0x7c,0x04,0x04,0x04,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x7c,0x40,0x40,0x7c,0x04,0x04,0x7c,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x7c,0x40,0x40,0x7c,0x44,0x44,0x7c,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x7c,0x04,0x04,0x04,0x04,0x04,0x04,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x7c,0x44,0x44,0x7c,0x44,0x44,0x7c,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x7c,0x44,0x44,0x7c,0x04,0x04,0x7c,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x10,0x00,0x10,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x10,0x00,0x10,0x20,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x08,0x10,0x20,0x40,0x20,0x10,0x08,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x7c,0x00,0x7c,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x20,0x10,0x08,0x04,0x08,0x10,0x20,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x7c,0x04,0x04,0x7c,0x00,0x00,0x10,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x7c,0x44,0x44,0x7c,0x40,0x40,0x7c,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x10,0x28,0x28,0x7c,0x44,0x44,0x44,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x7c,0x44,0x44,0x7c,0x44,0x44,0x7c,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x7c,0x40,0x40,0x40,0x40,0x40,0x7c,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x78,0x44,0x44,0x44,0x44,0x44,0x78,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x7c,0x40,0x40,0x7c,0x40,0x40,0x7c,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x7c,0x40,0x40,0x7c,0x40,0x40,0x40,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x7c,0x40,0x40,0x5c,0x44,0x44,0x7c,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x44,0x44,0x44,0x7c,0x44,0x44,0x44,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x10,0x10,0x10,0x10,0x10,0x10,0x10,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x7c,0x04,0x04,0x44,0x44,0x44,0x7c,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x44,0x48,0x70,0x40,0x60,0x58,0x44,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x40,0x40,0x40,0x40,0x40,0x40,0x7c,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x44,0x6c,0x6c,0x54,0x44,0x44,0x44,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x44,0x64,0x64,0x54,0x4c,0x4c,0x44,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x7c,0x44,0x44,0x44,0x44,0x44,0x7c,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x7c,0x44,0x44,0x7c,0x40,0x40,0x40,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x7c,0x44,0x44,0x54,0x4c,0x7c,0x04,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x7c,0x44,0x44,0x7c,0x60,0x58,0x44,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x7c,0x40,0x40,0x7c,0x04,0x04,0x7c,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x7c,0x10,0x10,0x10,0x10,0x10,0x10,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x44,0x44,0x44,0x44,0x44,0x44,0x7c,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x44,0x44,0x44,0x44,0x28,0x28,0x10,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x44,0x44,0x44,0x54,0x6c,0x6c,0x44,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x44,0x28,0x28,0x10,0x28,0x28,0x44,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x44,0x28,0x28,0x10,0x10,0x10,0x10,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x7c,0x08,0x08,0x10,0x20,0x20,0x7c,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x38,0x20,0x20,0x20,0x20,0x20,0x38,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x40,0x20,0x20,0x10,0x08,0x08,0x04,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x38,0x08,0x08,0x08,0x08,0x08,0x38,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x10,0x28,0x44,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x7c,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x20,0x10,0x08,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x10,0x28,0x28,0x7c,0x44,0x44,0x44,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x7c,0x44,0x44,0x7c,0x44,0x44,0x7c,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x7c,0x40,0x40,0x40,0x40,0x40,0x7c,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x78,0x44,0x44,0x44,0x44,0x44,0x78,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x7c,0x40,0x40,0x7c,0x40,0x40,0x7c,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x7c,0x40,0x40,0x7c,0x40,0x40,0x40,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x7c,0x40,0x40,0x5c,0x44,0x44,0x7c,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x44,0x44,0x44,0x7c,0x44,0x44,0x44,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x10,0x10,0x10,0x10,0x10,0x10,0x10,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x7c,0x04,0x04,0x44,0x44,0x44,0x7c,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x44,0x48,0x70,0x40,0x60,0x58,0x44,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x40,0x40,0x40,0x40,0x40,0x40,0x7c,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x44,0x6c,0x6c,0x54,0x44,0x44,0x44,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x44,0x64,0x64,0x54,0x4c,0x4c,0x44,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x7c,0x44,0x44,0x44,0x44,0x44,0x7c,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x7c,0x44,0x44,0x7c,0x40,0x40,0x40,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x7c,0x44,0x44,0x54,0x4c,0x7c,0x04,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x7c,0x44,0x44,0x7c,0x60,0x58,0x44,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x7c,0x40,0x40,0x7c,0x04,0x04,0x7c,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x7c,0x10,0x10,0x10,0x10,0x10,0x10,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x44,0x44,0x44,0x44,0x44,0x44,0x7c,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x44,0x44,0x44,0x44,0x28,0x28,0x10,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x44,0x44,0x44,0x54,0x6c,0x6c,0x44,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x44,0x28,0x28,0x10,0x28,0x28,0x44,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x44,0x28,0x28,0x10,0x10,0x10,0x10,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x7c,0x08,0x08,0x10,0x20,0x20,0x7c,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x08,0x10,0x10,0x20,0x10,0x10,0x08,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x10,0x10,0x10,0x10,0x10,0x10,0x10,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x20,0x10,0x10,0x08,0x10,0x10,0x20,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x24,0x58,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+    [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00],
+];
+
+/// State of the CSI SGR escape-sequence parser. Persists across `write_str` calls since a
+/// sequence may be split between two `print!`s.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum EscapeState {
+    /// Not currently in an escape sequence.
+    Ground,
+    /// Just saw `ESC` (`0x1b`); waiting to see if `[` follows to start a CSI sequence.
+    Escape,
+    /// Inside `ESC [ ... `, accumulating `;`-separated numeric params until a final byte.
+    Csi {
+        params: [u16; Self::MAX_PARAMS],
+        count: usize,
+        has_digit: bool,
+    },
+}
+
+impl EscapeState {
+    /// The maximum number of `;`-separated params tracked in a single CSI sequence. Additional
+    /// params are ignored.
+    const MAX_PARAMS: usize = 8;
+}
+
+/// State of the `$NAME$` color-markup token parser. Persists across `write_str` calls since a
+/// token may be split between two `print!`s.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum MarkupState {
+    /// Not currently inside a `$...$` token.
+    Ground,
+    /// Just saw the opening `$`; accumulating the token name until the closing `$`.
+    Token {
+        buf: [u8; Self::MAX_TOKEN_LEN],
+        len: usize,
+    },
+}
+
+impl MarkupState {
+    /// The maximum length of a token name. Longer tokens can never match, so a token-name
+    /// character arriving once this many are buffered ends the token as unterminated.
+    const MAX_TOKEN_LEN: usize = 16;
+}
+
+/// Converts a markup token name (e.g. `RED`, as used in `$RED$`) to the corresponding `Color`.
+fn color_from_markup_name(name: &str) -> Option<Color> {
+    Some(match name {
+        "BLACK" => Color::Black,
+        "BLUE" => Color::Blue,
+        "GREEN" => Color::Green,
+        "CYAN" => Color::Cyan,
+        "RED" => Color::Red,
+        "MAGENTA" => Color::Magenta,
+        "BROWN" => Color::Brown,
+        "LIGHT_GRAY" => Color::LightGray,
+        "DARK_GRAY" => Color::DarkGray,
+        "LIGHT_BLUE" => Color::LightBlue,
+        "LIGHT_GREEN" => Color::LightGreen,
+        "LIGHT_CYAN" => Color::LightCyan,
+        "LIGHT_RED" => Color::LightRed,
+        "LIGHT_MAGENTA" => Color::LightMagenta,
+        "YELLOW" => Color::Yellow,
+        "WHITE" => Color::White,
+        _ => return None,
+    })
+}
+
+/// Converts an ANSI SGR color index (0-7, as used in codes 30-37, 40-47, etc.) to the
+/// corresponding VGA `Color`. ANSI numbers its colors red=1/blue=4, while the VGA `Color` enum
+/// numbers them blue=1/red=4, so the red and blue bits must be swapped rather than passed
+/// through.
+fn ansi_color(index: u8) -> Color {
+    let swapped = (index & 0b001) << 2 | (index & 0b010) | (index & 0b100) >> 2;
+
+    // SAFETY: sound because `swapped` is limited to the range 0x0 to 0x7, and all discriminants
+    // in this range are defined in `Color`.
+    unsafe { core::mem::transmute(swapped) }
+}
 
 /// Returns a handle to the VGA `Console`. Writes to the `Console` are synchronized and are thus
 /// thread safe.
 pub fn console() -> Console {
-    const VIDEO_MEM_ADDR: u64 = 0xb8000;
-    static CONSOLE: Mutex<ConsoleData> = Mutex::new(ConsoleData {
-        video_mem: VIDEO_MEM_ADDR as *mut [ColoredGlyph; Console::WIDTH],
-        buffer: [[ColoredGlyph::null(Colors::new()); Console::WIDTH]; Console::BUFFER_LINES],
-        colors: Colors::new(),
-        loc: Location::new(),
-    });
+    static CONSOLE: Mutex<ConsoleData> = Mutex::new(ConsoleData::new());
     static INIT: spin::Once<()> = spin::Once::new();
 
     INIT.call_once(|| {
         // clear the screen
         CONSOLE.lock().scroll_and_flush(Location::default());
 
-        // hide the cursor
-        // TODO
+        // the cursor is hidden by default; this hides the hardware cursor and sets its shape for
+        // if/when a caller shows it with set_cursor_visible(true)
+        CONSOLE.lock().update_cursor_shape();
     });
 
     Console(&CONSOLE)
 }
 
+/// Returns a `Console` backed by its own `ConsoleData`, separate from the shared singleton
+/// returned by [`console`]. The panic handler uses this so a readable fatal screen can still be
+/// rendered even if the shared console's lock is already held, e.g. because the panic happened
+/// mid-write. Every call returns the same `Console`, so its lock is only guaranteed free on the
+/// first call.
+pub fn recover_console() -> Console {
+    static CONSOLE: Mutex<ConsoleData> = Mutex::new(ConsoleData::new());
+
+    Console(&CONSOLE)
+}
+
+/// Returns a handle to a `Console` rendering into the linear framebuffer at `base`, instead of
+/// the VGA text-mode singleton returned by [`console`]. As with [`console`], the underlying
+/// `ConsoleData` is only initialized (and `base`/`pitch`/`bpp` only used) on the first call;
+/// later calls return the same `Console` regardless of the arguments passed. Writes to the
+/// `Console` are synchronized and are thus thread safe.
+///
+/// # Safety
+///
+/// `bpp` must be `3` or `4`, and `base` must point to a writable framebuffer of at least
+/// `Console::HEIGHT * FONT_HEIGHT` rows spaced `pitch` bytes apart, each with room for
+/// `Console::WIDTH * FONT_WIDTH` pixels, that remains valid for the remaining lifetime of the
+/// program.
+pub unsafe fn framebuffer_console(base: *mut u8, pitch: usize, bpp: u8) -> Console {
+    static CONSOLE: spin::Once<Mutex<ConsoleData>> = spin::Once::new();
+    static INIT: spin::Once<()> = spin::Once::new();
+
+    let console = CONSOLE.call_once(|| {
+        let backend = Backend::Framebuffer(FramebufferConsole::new(base, pitch, bpp));
+        Mutex::new(ConsoleData::new_with_backend(backend))
+    });
+
+    INIT.call_once(|| {
+        // clear the screen
+        console.lock().scroll_and_flush(Location::default());
+    });
+
+    Console(console)
+}
+
 /// Helper function for the `print!` macro.
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
+    // write to the serial port first so output survives even if the console write below hangs
+    // or the display is wedged
+    if crate::serial::mirror_enabled() {
+        crate::serial::com1().write_fmt(args).expect("INFALLIBLE");
+    }
+
     console().write_fmt(args).expect("INFALLIBLE");
 }
 
@@ -184,6 +1004,20 @@ macro_rules! println {
     })
 }
 
+/// Prints to the screen with a newline, like [`println`](macro.println.html), but with the
+/// `$NAME$` inline color-markup dialect (e.g. `$RED$`, `$BG_BLUE$`, `$RESET$`) enabled for the
+/// duration of the call. The `Console`'s markup setting is restored to whatever it was
+/// beforehand once the call completes.
+#[macro_export]
+macro_rules! println_colored {
+    ($($arg:tt)*) => ({
+        let console = $crate::vga::console();
+        let was_enabled = console.set_markup_enabled(true);
+        $crate::println!($($arg)*);
+        console.set_markup_enabled(was_enabled);
+    })
+}
+
 /// A glyph, corresponding to [Code page 437](https://en.wikipedia.org/wiki/Code_page_437) which can
 /// be written to the screen.
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Default)]
@@ -335,6 +1169,16 @@ impl Colors {
     }
 }
 
+impl Color {
+    /// Returns the bright variant of this `Color`.
+    fn brighten(self) -> Color {
+        // SAFETY: this is sound because `self as u8` is limited to the range 0x0 to 0xf, so
+        // setting bit 0x8 stays in that same range, and all discriminants in it are defined in
+        // `Color`.
+        unsafe { core::mem::transmute(self as u8 | 0x8) }
+    }
+}
+
 impl Default for Colors {
     fn default() ->  Colors {
         Colors::new()